@@ -4,9 +4,11 @@
 //!
 //! `UPSafeCell<OSInodeInner>` -> `OSInode`: for static `ROOT_INODE`,we
 //! need to wrap `OSInodeInner` into `UPSafeCell`
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
 use alloc::collections::VecDeque;
 use alloc::string::String;
-use super::{File, Stat, StatMode};
+use super::{File, Stat, StatMode, FIONBIO, FIONREAD, SEEK_CUR, SEEK_END, SEEK_SET};
 use crate::drivers::BLOCK_DEVICE;
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
@@ -27,7 +29,7 @@ pub struct OSInodeManager {
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    stat: Stat,
+    stat_mode: StatMode,
     name: String,
     inner: UPSafeCell<OSInodeInner>,
 }
@@ -35,25 +37,20 @@ pub struct OSInode {
 pub struct OSInodeInner {
     offset: usize,
     inode: Arc<Inode>,
-}
-
-pub struct LinkName {
-    old_path: String,
-    new_path: String,
-}
-
-pub struct LinkManager {
-    name_queue: VecDeque<Arc<LinkName>>,
+    /// Cached result of `file_size`'s block walk, invalidated on every
+    /// `write` since appending can grow the file. Without this, `SEEK_END`
+    /// and `FIONREAD` each re-read the whole file in 512-byte chunks.
+    size: Option<usize>,
 }
 
 impl OSInode {
     /// create a new inode in memory
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>, ino: u64, nlink: u32,stat_mode: StatMode, name: String) -> Self {
+    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>, stat_mode: StatMode, name: String) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
-            stat: Stat::new(ino, nlink, stat_mode),
+            stat_mode,
+            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode, size: None }) },
             name,
         }
     }
@@ -72,6 +69,25 @@ impl OSInode {
         }
         v
     }
+
+    /// total byte length of the backing inode, needed for end-relative seeks
+    fn file_size(&self) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        if let Some(size) = inner.size {
+            return size;
+        }
+        let mut buffer = [0u8; 512];
+        let mut offset = 0usize;
+        loop {
+            let len = inner.inode.read_at(offset, &mut buffer);
+            if len == 0 {
+                break;
+            }
+            offset += len;
+        }
+        inner.size = Some(offset);
+        offset
+    }
 }
 
 lazy_static! {
@@ -86,7 +102,6 @@ pub fn list_apps() {
     println!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
         println!("{}", app);
-        LINK_MANAGER.exclusive_access().add(app.clone().as_str(), "none_name_just_test_made_by_OSFantasy");
     }
     println!("**************/");
 }
@@ -123,37 +138,125 @@ impl OpenFlags {
 
 
 
+lazy_static! {
+    /// Name -> backing inode for every file reachable by a path, seeded from
+    /// the on-disk root directory on first use. `easy_fs` isn't vendored into
+    /// this tree (there's no crate source to extend with a real dirent/refcount
+    /// format), so a hard link (two names, one inode) is tracked here instead:
+    /// two keys holding `Arc::ptr_eq` inodes *are* the link, and its count is
+    /// how many keys currently point at it.
+    static ref DENTRIES: UPSafeCell<BTreeMap<String, Arc<Inode>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+    /// Names removed by [`unlink_at`] that must stay gone even though the
+    /// on-disk directory entry `ROOT_INODE.find` would still turn up (we can't
+    /// remove it for real without a real `easy_fs` directory-entry API). A
+    /// name is cleared from here the moment it's reused by [`open_file`]'s
+    /// create path or [`link_at`].
+    static ref UNLINKED: UPSafeCell<BTreeSet<String>> = unsafe { UPSafeCell::new(BTreeSet::new()) };
+}
+
+fn ensure_seeded() {
+    let mut dentries = DENTRIES.exclusive_access();
+    if dentries.is_empty() {
+        for name in ROOT_INODE.ls() {
+            if let Some(inode) = ROOT_INODE.find(&name) {
+                dentries.insert(name, inode);
+            }
+        }
+    }
+}
+
+/// Resolve `name` to its backing inode, consulting [`DENTRIES`] before
+/// falling back to a fresh on-disk lookup (e.g. for a file `create`d after
+/// the table was last seeded). A name torn down by [`unlink_at`] stays gone:
+/// it's never resurrected by re-discovering the still-present on-disk dirent.
+fn lookup(name: &str) -> Option<Arc<Inode>> {
+    if UNLINKED.exclusive_access().contains(name) {
+        return None;
+    }
+    ensure_seeded();
+    let mut dentries = DENTRIES.exclusive_access();
+    if let Some(inode) = dentries.get(name) {
+        return Some(inode.clone());
+    }
+    let inode = ROOT_INODE.find(name)?;
+    dentries.insert(String::from(name), inode.clone());
+    Some(inode)
+}
+
+/// How many [`DENTRIES`] names currently resolve to `inode`
+fn link_count(inode: &Arc<Inode>) -> u32 {
+    ensure_seeded();
+    DENTRIES
+        .exclusive_access()
+        .values()
+        .filter(|candidate| Arc::ptr_eq(candidate, inode))
+        .count() as u32
+}
+
 /// Open a file
 pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-
-    let mut link_manager = LINK_MANAGER.exclusive_access();
-    let (name, nlink, index)= link_manager.all(name, flags.clone());
     if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+        if let Some(inode) = lookup(name) {
             // clear size
             inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode, index as u64, nlink as u32, StatMode::FILE, String::from(name))))
+            Some(Arc::new(OSInode::new(readable, writable, inode, StatMode::FILE, String::from(name))))
         } else {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode, index as u64, nlink as u32, StatMode::FILE, String::from(name))))
+            ROOT_INODE.create(name).map(|inode| {
+                DENTRIES.exclusive_access().insert(String::from(name), inode.clone());
+                UNLINKED.exclusive_access().remove(name);
+                Arc::new(OSInode::new(readable, writable, inode, StatMode::FILE, String::from(name)))
+            })
         }
     } else {
-        if nlink != 0 {
-        ROOT_INODE.find(name).map(|inode| {
+        lookup(name).map(|inode| {
             if flags.contains(OpenFlags::TRUNC) {
                 inode.clear();
             }
-            Arc::new(OSInode::new(readable, writable, inode, index as u64, nlink as u32, StatMode::FILE, String::from(name)))
+            Arc::new(OSInode::new(readable, writable, inode, StatMode::FILE, String::from(name)))
         })
-        } else {
-            None
-        }
     }
 }
 
+/// Create a second directory entry `new_name` pointing at the same inode as
+/// `old_name`, bumping its link count. Returns `-1` if `old_name` doesn't
+/// exist or `new_name` is already taken.
+pub fn link_at(old_name: &str, new_name: &str) -> isize {
+    if old_name == new_name {
+        return -1;
+    }
+    let inode = match lookup(old_name) {
+        Some(inode) => inode,
+        None => return -1,
+    };
+    if lookup(new_name).is_some() {
+        return -1;
+    }
+    DENTRIES.exclusive_access().insert(String::from(new_name), inode);
+    UNLINKED.exclusive_access().remove(new_name);
+    0
+}
+
+/// Remove the `name` directory entry and, once `name` was the last one
+/// pointing at that inode, truncate it to free its data blocks. The name is
+/// also tombstoned in [`UNLINKED`] so a later `lookup` can't resurrect it by
+/// re-discovering the on-disk dirent `ROOT_INODE.find` still sees (there's no
+/// real `easy_fs` directory-entry removal available in this tree).
+pub fn unlink_at(name: &str) -> isize {
+    let inode = match lookup(name) {
+        Some(inode) => inode,
+        None => return -1,
+    };
+    DENTRIES.exclusive_access().remove(name);
+    UNLINKED.exclusive_access().insert(String::from(name));
+    if link_count(&inode) == 0 {
+        inode.clear();
+    }
+    0
+}
+
 // pub fn update_file(name: &str, flags: OpenFlags){
 //
 // }
@@ -193,112 +296,227 @@ impl File for OSInode {
             inner.offset += write_size;
             total_write_size += write_size;
         }
+        // A write can grow the file; force the next `file_size` call to
+        // re-walk the inode instead of trusting a now possibly-stale cache.
+        inner.size = None;
         total_write_size
     }
 
     fn file_stat(&self) -> Stat {
-        let mut stat = self.stat.clone();
-        let name = self.name.as_str();
-        let mut link_manager = LINK_MANAGER.exclusive_access();
-        let (name, nlink, index)= link_manager.all(name, OpenFlags::RDWR);
-        stat.nlink = nlink as u32;
-        stat.ino = index as u64;
-        stat
+        let inner = self.inner.exclusive_access();
+        // `easy_fs::Inode` exposes no stable on-disk id, so its `Arc`'s
+        // address stands in for one: stable for as long as any name in
+        // `DENTRIES` keeps the inode alive, which is exactly the lifetime
+        // `ino` needs to be meaningful over.
+        let ino = Arc::as_ptr(&inner.inode) as usize as u64;
+        Stat::new(ino, link_count(&inner.inode), self.stat_mode)
     }
-}
 
-impl LinkManager {
-    ///Creat an empty TaskManager
-    pub fn new() -> Self {
-        Self {
-            name_queue: VecDeque::new(),
+    fn seek(&self, offset: isize, whence: usize) -> isize {
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => self.inner.exclusive_access().offset as isize,
+            SEEK_END => self.file_size() as isize,
+            _ => return -1,
+        };
+        let new_offset = base + offset;
+        if new_offset < 0 {
+            return -1;
         }
+        self.inner.exclusive_access().offset = new_offset as usize;
+        new_offset
     }
 
-    pub fn all<'a>(&'a mut self, name: &'a str, flags: OpenFlags) -> (&'a str, usize, usize) {
-        if flags.contains(OpenFlags::CREATE) {
-            println!("[Kernel][link]all , add:{}", name.clone());
-            self.add(name.clone(), "none_name_just_test_made_by_OSFantasy");
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        match cmd {
+            FIONREAD => {
+                // `offset` can be seek'd past `file_size()`; saturate instead
+                // of underflowing.
+                let remaining = self
+                    .file_size()
+                    .saturating_sub(self.inner.exclusive_access().offset);
+                write_usize_to_user(arg, remaining);
+                0
+            }
+            // Regular files have no notion of blocking; accept and ignore.
+            FIONBIO => 0,
+            _ => -1,
         }
-        let fetched_name = self.fetch(name);
-        let nlink = self.find_num(&fetched_name);
-        let index = self.find_index(&fetched_name);
-        (fetched_name, nlink, index)
     }
+}
 
-    /// Add process back to ready queue
-    pub fn add(&mut self, old_name: &str, new_name: &str) -> isize {
-        if old_name == new_name {
-            return -1;
-        }
+/// Copy a `usize` into the user page(s) at virtual address `ptr`, used to
+/// answer `ioctl` commands like `FIONREAD`
+fn write_usize_to_user(ptr: usize, value: usize) {
+    let token = crate::task::current_user_token();
+    let buffers = crate::mm::translated_byte_buffer(token, ptr as *const u8, core::mem::size_of::<usize>());
+    let bytes = value.to_ne_bytes();
+    let mut copied = 0;
+    for slice in buffers {
+        let len = slice.len();
+        slice.copy_from_slice(&bytes[copied..copied + len]);
+        copied += len;
+    }
+}
 
-        let link_name = LinkName {
-            old_path: old_name.parse().unwrap(),
-            new_path: new_name.parse().unwrap(),
-        };
-        self.name_queue.push_back(Arc::from(link_name));
-        0
+/// Which `/proc`-style view a [`ProcFile`] renders
+#[derive(Copy, Clone)]
+pub enum ProcFileKind {
+    /// `/proc/<pid>/status`: task status, start time and accumulated time
+    Status,
+    /// `/proc/<pid>/maps`: ranges installed through `sys_mmap`
+    Maps,
+    /// `/proc/self/syscalls`: per-syscall invocation counts
+    Syscalls,
+}
+
+/// The inner state of a [`ProcFile`]
+struct ProcFileInner {
+    offset: usize,
+    /// Lazily rendered on first access, since the content is generated from
+    /// live task state rather than stored on disk
+    content: Option<Vec<u8>>,
+}
+
+/// A read-only pseudo file that serializes the current task's scheduling
+/// and syscall accounting on demand, instead of backing it with a real inode
+pub struct ProcFile {
+    kind: ProcFileKind,
+    inner: UPSafeCell<ProcFileInner>,
+}
+
+impl ProcFile {
+    /// Create a new proc file of the given kind
+    pub fn new(kind: ProcFileKind) -> Self {
+        Self {
+            kind,
+            inner: unsafe { UPSafeCell::new(ProcFileInner { offset: 0, content: None }) },
+        }
     }
 
-    pub fn remove(&mut self, name: &str) -> isize {
-        let mut result: isize = -1;
-        let mut remove_index: usize = 0;
-
-        for (index, link_name) in self.name_queue.iter().enumerate() {
-            let old_name = link_name.old_path.as_str();
-            let new_name = link_name.new_path.as_str();
-            if old_name == name || new_name == name  {
-                remove_index = index;
-                result = 0;
-                println!("find remove_index is {}, old_name = {}, new_name = {}", remove_index, old_name, new_name);
-                break;
+    fn render(&self) -> Vec<u8> {
+        use crate::task::{current_task, current_task_mmap_ranges, get_current_processor_info};
+        let mut s = String::new();
+        match self.kind {
+            ProcFileKind::Status => {
+                let info = get_current_processor_info();
+                let start_time = current_task().unwrap().inner_exclusive_access().task_start_time;
+                s.push_str(&alloc::format!("Status:\t{}\n", info.status()));
+                s.push_str(&alloc::format!("StartTime:\t{} ms\n", start_time));
+                s.push_str(&alloc::format!("Time:\t{} ms\n", info.time()));
+            }
+            ProcFileKind::Maps => {
+                for (start, end) in current_task_mmap_ranges() {
+                    s.push_str(&alloc::format!("{:#x}-{:#x}\n", start, end));
+                }
+            }
+            ProcFileKind::Syscalls => {
+                let info = get_current_processor_info();
+                for (id, times) in info.syscall_times().iter().enumerate() {
+                    if *times > 0 {
+                        s.push_str(&alloc::format!("syscall[{}]:\t{}\n", id, times));
+                    }
+                }
             }
         }
+        s.into_bytes()
+    }
+}
 
-        if result == 0 {
-            self.name_queue.remove(remove_index);
-        }
+/// Open one of the `/proc`-style introspection files. Accepts both
+/// `/proc/self/...` and `/proc/<pid>/...`: this tree has no pid-indexed task
+/// table, only "whichever task is currently running on this hart", so the
+/// `<pid>` segment is accepted but not actually looked up — every form
+/// renders the calling task's own view, same as `self`.
+pub fn open_proc_file(name: &str) -> Option<Arc<ProcFile>> {
+    let rest = name.strip_prefix("/proc/")?;
+    let mut parts = rest.splitn(2, '/');
+    let _pid_or_self = parts.next()?;
+    let kind = match parts.next()? {
+        "status" => ProcFileKind::Status,
+        "maps" => ProcFileKind::Maps,
+        "syscalls" => ProcFileKind::Syscalls,
+        _ => return None,
+    };
+    Some(Arc::new(ProcFile::new(kind)))
+}
 
-        result
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
     }
-    /// Take a process out of the ready queue
-    pub fn fetch<'a>(&'a self, name: &'a str) -> &'a str {
-        if let Some(index) = self.name_queue.iter().position(|link_name| {
-            Arc::clone(link_name).old_path == name || Arc::clone(link_name).new_path == name
-        }) {
-            self.name_queue[index].old_path.as_str()
-        } else {
-            println!("[Kernel][fs][inode]Not fetch the name in LINK_MANAGER");
-            name
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.content.is_none() {
+            let content = self.render();
+            inner.content = Some(content);
         }
+        let content = inner.content.clone().unwrap();
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let remain = content.len().saturating_sub(inner.offset);
+            if remain == 0 {
+                break;
+            }
+            let len = remain.min(slice.len());
+            slice[..len].copy_from_slice(&content[inner.offset..inner.offset + len]);
+            inner.offset += len;
+            total_read_size += len;
+        }
+        total_read_size
     }
-
-    pub fn find_num(&self, name: &str) -> usize {
-        let count = self.name_queue.iter().filter(|link_name| {
-            Arc::clone(link_name).old_path == name
-        }).count();
-
-        if count == 0 {
-            println!("[Kernel][fs][inode] Not fetch the name in LINK_MANAGER");
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn file_stat(&self) -> Stat {
+        Stat::new(0, 1, StatMode::FILE)
+    }
+    fn seek(&self, offset: isize, whence: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.content.is_none() {
+            let content = self.render();
+            inner.content = Some(content);
         }
-
-        count
+        let len = inner.content.as_ref().unwrap().len() as isize;
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => inner.offset as isize,
+            SEEK_END => len,
+            _ => return -1,
+        };
+        let new_offset = base + offset;
+        if new_offset < 0 {
+            return -1;
+        }
+        inner.offset = new_offset as usize;
+        new_offset
     }
 
-    pub fn find_index(&self, name: &str) -> usize {
-        if let Some(index) = self.name_queue.iter().position(|link_name| {
-            Arc::clone(link_name).old_path == name
-        }) {
-            return index;
-        } else {
-            self.name_queue.len()
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        match cmd {
+            FIONREAD => {
+                let mut inner = self.inner.exclusive_access();
+                if inner.content.is_none() {
+                    let content = self.render();
+                    inner.content = Some(content);
+                }
+                // `offset` can be seek'd past the content length; saturate
+                // instead of underflowing.
+                let remaining = inner
+                    .content
+                    .as_ref()
+                    .unwrap()
+                    .len()
+                    .saturating_sub(inner.offset);
+                drop(inner);
+                write_usize_to_user(arg, remaining);
+                0
+            }
+            FIONBIO => 0,
+            _ => -1,
         }
     }
-
-}
-
-lazy_static! {
-    /// TASK_MANAGER instance through lazy_static!
-    pub static ref LINK_MANAGER: UPSafeCell<LinkManager> =
-        unsafe { UPSafeCell::new(LinkManager::new()) };
 }
\ No newline at end of file
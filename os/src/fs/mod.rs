@@ -17,8 +17,37 @@ pub trait File: Send + Sync {
     fn write(&self, buf: UserBuffer) -> usize;
 
     fn file_stat(&self) -> Stat;
+
+    /// Reposition the file offset according to `whence` (`SEEK_SET`/`SEEK_CUR`/
+    /// `SEEK_END`), returning the resulting absolute offset or `-1` if the
+    /// requested offset would be negative. Not every file is seekable, so the
+    /// default rejects it; `Stdin`/`Stdout` rely on that default rather than
+    /// overriding it.
+    fn seek(&self, _offset: isize, _whence: usize) -> isize {
+        -1
+    }
+
+    /// Device-specific control, e.g. `FIONREAD`/`FIONBIO`. Unsupported
+    /// commands should return `-1` rather than panicking; the default rejects
+    /// everything, which is all `Stdin`/`Stdout` need.
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        -1
+    }
 }
 
+/// Query the number of bytes immediately available to read; `arg` points at
+/// a `usize` to fill in
+pub const FIONREAD: usize = 0x541B;
+/// Toggle non-blocking mode; `arg` points at an `i32` that is zero/nonzero
+pub const FIONBIO: usize = 0x5421;
+
+/// Seek relative to the start of the file
+pub const SEEK_SET: usize = 0;
+/// Seek relative to the current offset
+pub const SEEK_CUR: usize = 1;
+/// Seek relative to the end of the file
+pub const SEEK_END: usize = 2;
+
 /// The stat of a inode
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -62,5 +91,5 @@ impl Stat {
     }
 }
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags, LINK_MANAGER};
+pub use inode::{link_at, list_apps, open_file, open_proc_file, unlink_at, OSInode, OpenFlags, ProcFile, ProcFileKind};
 pub use stdio::{Stdin, Stdout};
@@ -0,0 +1,456 @@
+//! Frame-owning memory areas for `mmap`-backed regions
+//!
+//! `mm_map` used to call `frame_alloc().map(|frame| frame.ppn)` and let the
+//! `FrameTracker` drop at the end of the loop body, so the allocator thought
+//! the frame was free again the instant after the page table started
+//! pointing at it. [`MapArea`] fixes that by holding on to every
+//! [`FrameTracker`] it maps for as long as the area is alive, and
+//! [`MemorySet`] keeps the areas for one address space together so
+//! `remove_area_with_start_vpn` can unmap and drop them as a unit. A task's
+//! [`TaskControlBlock`](crate::task::TaskControlBlock) only stores its `satp`
+//! token rather than owning a `MemorySet` directly in this tree, so
+//! [`MemorySet`] is looked up by token instead of being threaded through the
+//! task structures.
+//!
+//! A page shared copy-on-write between a parent and a child (see
+//! [`MemorySet::clone_cow`]) is backed by one `Arc<FrameTracker>` cloned into
+//! both sides' `frames` maps, rather than two independent `FrameTracker`s for
+//! the same physical page: `FrameTracker`'s `Drop` lives in the frame
+//! allocator, outside this tree, so it has no way to know the page is shared.
+//! Wrapping it in `Arc` here gets the same effect without touching that
+//! `Drop` impl — the frame is only actually freed when the last `Arc` (parent
+//! or child, whichever drops last) goes away.
+
+use super::page_table::{PTEFlags, PageSize, PageTable};
+use super::{frame_alloc, FrameTracker, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+
+bitflags! {
+    /// Permission bits for a [`MapArea`]. Bit positions line up with
+    /// [`PTEFlags`] (minus `V`), so they lower into it with a plain
+    /// `from_bits_truncate`.
+    pub struct MapPermission: u8 {
+        const R = 1 << 1;
+        const W = 1 << 2;
+        const X = 1 << 3;
+        const U = 1 << 4;
+    }
+}
+
+impl MapPermission {
+    /// Build from the low `R`/`W`/`X` bits `sys_mmap`'s `port` argument
+    /// carries (bit 0/1/2); mmap'd pages are always user-accessible.
+    pub fn from_port(port: usize) -> Self {
+        Self::from_bits_truncate(((port as u8) << 1) | Self::U.bits())
+    }
+}
+
+/// How a [`MapArea`]'s virtual pages are backed
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MapType {
+    /// Physical page number equals virtual page number
+    Identical,
+    /// Each page (or huge leaf) gets its own freshly allocated frame
+    Framed,
+}
+
+/// A virtual page range plus the frames backing it, so the frames live for
+/// exactly as long as the area is mapped
+pub struct MapArea {
+    vpn_start: VirtPageNum,
+    vpn_end: VirtPageNum,
+    frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    map_type: MapType,
+    map_perm: MapPermission,
+    /// If set, `map` installs no leaf PTEs up front; pages are allocated
+    /// and mapped one at a time by `handle_lazy_fault` on first touch
+    lazy: bool,
+}
+
+impl MapArea {
+    pub fn new(start_va: VirtAddr, end_va: VirtAddr, map_type: MapType, map_perm: MapPermission, lazy: bool) -> Self {
+        Self {
+            vpn_start: start_va.floor(),
+            vpn_end: end_va.ceil(),
+            frames: BTreeMap::new(),
+            map_type,
+            map_perm,
+            lazy,
+        }
+    }
+
+    /// First VPN of this area, used as its identity by
+    /// `remove_area_with_start_vpn`
+    pub fn start_vpn(&self) -> VirtPageNum {
+        self.vpn_start
+    }
+
+    /// Map every page in `[vpn_start, vpn_end)` into `page_table`. A `lazy`
+    /// area installs nothing here; its pages are faulted in one at a time by
+    /// `handle_lazy_fault`.
+    ///
+    /// Both `Identical` and `Framed` areas are mapped page-by-page at
+    /// `Size4KiB`; no huge leaves are installed here. For `Framed` areas each
+    /// leaf is backed by one `frame_alloc()` call, which only ever hands back
+    /// a single 4 KiB frame, not a physically-contiguous run of them, so a
+    /// 2 MiB/1 GiB leaf's `ppn<<12` pointing at that one frame would let
+    /// every page beyond the first alias whatever physical memory happens to
+    /// follow it. `largest_fitting_page_size` is not called from here.
+    fn map(&mut self, page_table: &mut PageTable) {
+        if self.lazy {
+            return;
+        }
+        let flags = PTEFlags::from_bits_truncate(self.map_perm.bits());
+        let mut vpn = self.vpn_start;
+        while vpn < self.vpn_end {
+            match self.map_type {
+                MapType::Identical => {
+                    page_table.map_huge(vpn, PhysPageNum::from(vpn.0), flags, PageSize::Size4KiB);
+                    vpn = VirtPageNum(vpn.0 + 1);
+                }
+                MapType::Framed => {
+                    let frame = frame_alloc().unwrap();
+                    page_table.map_huge(vpn, frame.ppn, flags, PageSize::Size4KiB);
+                    self.frames.insert(vpn, Arc::new(frame));
+                    vpn = VirtPageNum(vpn.0 + 1);
+                }
+            }
+        }
+    }
+
+    /// Unmap every page this area mapped and drop its `FrameTracker`s,
+    /// returning the physical frames to the allocator
+    fn unmap(&mut self, page_table: &mut PageTable) {
+        for &vpn in self.frames.keys() {
+            page_table.unmap(vpn);
+        }
+        self.frames.clear();
+        if self.map_type == MapType::Identical {
+            let mut vpn = self.vpn_start;
+            while vpn < self.vpn_end {
+                page_table.unmap(vpn);
+                vpn = VirtPageNum(vpn.0 + 1);
+            }
+        }
+    }
+
+    /// Copy `data` into the area starting at its first page, page by page;
+    /// `data.len()` must not exceed the area's span
+    fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+        let mut vpn = self.vpn_start;
+        let mut start = 0;
+        let len = data.len();
+        while start < len {
+            let src = &data[start..len.min(start + PageSize::Size4KiB.size())];
+            let dst = &mut page_table
+                .translate(vpn)
+                .unwrap()
+                .ppn()
+                .get_bytes_array()[..src.len()];
+            dst.copy_from_slice(src);
+            start += PageSize::Size4KiB.size();
+            vpn = VirtPageNum(vpn.0 + 1);
+        }
+    }
+}
+
+/// The set of [`MapArea`]s mapped into one address space's page table
+pub struct MemorySet {
+    page_table: PageTable,
+    areas: Vec<MapArea>,
+}
+
+impl MemorySet {
+    /// Wrap an already-running address space, identified by its `satp`
+    /// token, so `mmap`-style areas pushed onto it own their frames
+    fn from_token(token: usize) -> Self {
+        Self {
+            page_table: PageTable::from_token(token),
+            areas: Vec::new(),
+        }
+    }
+
+    /// Map a fresh `Framed` area over `[start_va, end_va)` up front, with no
+    /// initializer data
+    pub fn insert_framed_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) {
+        self.push(MapArea::new(start_va, end_va, MapType::Framed, perm, false), None);
+    }
+
+    /// Register `[start_va, end_va)` as `Framed` without mapping anything:
+    /// `handle_lazy_fault` allocates and maps each page the first time it's
+    /// touched, as `sys_mmap` needs for large sparse regions
+    pub fn insert_lazy_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) {
+        self.push(MapArea::new(start_va, end_va, MapType::Framed, perm, true), None);
+    }
+
+    /// Map `area` into this set's page table, optionally copying in `data`,
+    /// then keep it alive in `areas`
+    pub fn push(&mut self, mut area: MapArea, data: Option<&[u8]>) {
+        area.map(&mut self.page_table);
+        if let Some(data) = data {
+            area.copy_data(&mut self.page_table, data);
+        }
+        let token = self.page_table.token();
+        for &vpn in area.frames.keys() {
+            super::swap::track_resident(token, vpn);
+        }
+        self.areas.push(area);
+    }
+
+    /// Unmap and drop the area starting at `start_vpn`, returning its
+    /// frames to the allocator; `-1` if no such area is mapped
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) -> isize {
+        match self.areas.iter().position(|area| area.start_vpn() == start_vpn) {
+            Some(idx) => {
+                let mut area = self.areas.remove(idx);
+                let token = self.page_table.token();
+                for &vpn in area.frames.keys() {
+                    super::swap::untrack_resident(token, vpn);
+                }
+                area.unmap(&mut self.page_table);
+                0
+            }
+            None => -1,
+        }
+    }
+
+    /// Duplicate every `Framed` area's mapping into `child_token`'s address
+    /// space for a copy-on-write `fork`: both sides end up pointing at the
+    /// *same* physical frame, read-only with the copy-on-write marker set,
+    /// and the frame's shared refcount goes up by one.
+    ///
+    /// This only duplicates the `mmap` areas this module tracks. A real
+    /// `fork` also needs the code/data/stack/trampoline mappings copied,
+    /// which is done by address-space setup code that lives outside this
+    /// snapshot; `child_token` must already name a page table with those
+    /// non-`Framed` regions in place before calling this. The shared frame
+    /// itself is safe regardless of drop order: both sides hold an
+    /// `Arc<FrameTracker>` clone, so it's only actually freed once neither
+    /// the parent nor any child referencing it is left, not when one
+    /// particular side happens to exit or unmap first.
+    pub fn clone_cow(&mut self, child_token: usize) -> MemorySet {
+        let mut child = MemorySet::from_token(child_token);
+        for area in &self.areas {
+            if area.map_type != MapType::Framed {
+                continue;
+            }
+            let mut child_area = MapArea {
+                vpn_start: area.vpn_start,
+                vpn_end: area.vpn_end,
+                frames: BTreeMap::new(),
+                map_type: area.map_type,
+                map_perm: area.map_perm,
+                lazy: area.lazy,
+            };
+            let ro_flags = PTEFlags::from_bits_truncate(area.map_perm.bits()) & !PTEFlags::W;
+            for (&vpn, frame) in area.frames.iter() {
+                self.page_table.mark_cow(vpn);
+                child.page_table.map_huge(vpn, frame.ppn, ro_flags, PageSize::Size4KiB);
+                child.page_table.mark_cow(vpn);
+                child_area.frames.insert(vpn, frame.clone());
+            }
+            child.areas.push(child_area);
+        }
+        child
+    }
+}
+
+/// Run `f` against the page table of the address space named by `token`,
+/// if one has been touched by `mm_map` yet. Lets sibling modules like
+/// [`super::swap`] operate on a `MemorySet`'s page table without reaching
+/// into its private fields directly.
+pub(crate) fn with_page_table<R>(token: usize, f: impl FnOnce(&mut PageTable) -> R) -> Option<R> {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    sets.get_mut(&token).map(|set| f(&mut set.page_table))
+}
+
+/// Remove and return the `FrameTracker` backing `vpn`, without touching its
+/// PTE — used when swapping a page out, so the owning area stops thinking
+/// it holds the (about to be freed) physical frame
+pub(crate) fn take_resident_frame(token: usize, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    let set = sets.get_mut(&token)?;
+    set.areas.iter_mut().find_map(|area| area.frames.remove(&vpn))
+}
+
+/// Hand a freshly allocated `FrameTracker` back to whichever area covers
+/// `vpn`, e.g. after a swap-in; `-1` if no framed area covers it
+pub(crate) fn install_resident_frame(token: usize, vpn: VirtPageNum, frame: Arc<FrameTracker>) -> isize {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    match sets.get_mut(&token) {
+        Some(set) => match set
+            .areas
+            .iter_mut()
+            .find(|area| vpn >= area.vpn_start && vpn < area.vpn_end)
+        {
+            Some(area) => {
+                area.frames.insert(vpn, frame);
+                0
+            }
+            None => -1,
+        },
+        None => -1,
+    }
+}
+
+/// Resolve a store page fault on a copy-on-write page: if this side holds
+/// the only `Arc<FrameTracker>` reference left, just re-enable `W` on the
+/// same frame; otherwise allocate a fresh frame, copy the 4 KiB page, and
+/// remap the faulting address space onto the copy, dropping this side's
+/// share of the old one. Returns `-1` if `vpn` isn't mapped copy-on-write,
+/// which the trap handler (outside this snapshot) should treat as a real
+/// fault rather than retry.
+pub fn handle_cow_fault(token: usize, vpn: VirtPageNum) -> isize {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    let set = match sets.get_mut(&token) {
+        Some(set) => set,
+        None => return -1,
+    };
+    let pte = match set.page_table.translate(vpn) {
+        Some(pte) if pte.is_cow() => pte,
+        _ => return -1,
+    };
+    let old_ppn = pte.ppn();
+    let write_flags = pte.flags() | PTEFlags::W;
+
+    let shared = match set
+        .areas
+        .iter()
+        .find(|area| vpn >= area.vpn_start && vpn < area.vpn_end)
+        .and_then(|area| area.frames.get(&vpn))
+    {
+        Some(frame) => frame.clone(),
+        None => return -1,
+    };
+
+    // `shared` plus the one still sitting in `area.frames` makes 2 when this
+    // side is the sole owner; anything higher means another address space is
+    // still pointing at the same physical frame.
+    if Arc::strong_count(&shared) <= 2 {
+        drop(shared);
+        return set.page_table.remap(vpn, old_ppn, write_flags);
+    }
+
+    let frame = match frame_alloc() {
+        Some(frame) => frame,
+        None => return -1,
+    };
+    frame
+        .ppn
+        .get_bytes_array()
+        .copy_from_slice(old_ppn.get_bytes_array());
+    let result = set.page_table.remap(vpn, frame.ppn, write_flags);
+    drop(shared);
+    if let Some(area) = set
+        .areas
+        .iter_mut()
+        .find(|area| vpn >= area.vpn_start && vpn < area.vpn_end)
+    {
+        // Replacing the map entry drops this side's `Arc` on the old shared
+        // frame; it's only actually freed once every other side referencing
+        // it has done the same.
+        area.frames.insert(vpn, Arc::new(frame));
+        super::swap::track_resident(token, vpn);
+    }
+    result
+}
+
+lazy_static! {
+    /// One [`MemorySet`] per address space, keyed by its `satp` token, built
+    /// lazily on the first `mm_map` call since tasks don't own a
+    /// `MemorySet` of their own in this tree
+    static ref MEMORY_SETS: UPSafeCell<BTreeMap<usize, MemorySet>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Register `[start, start+len)` as a user-accessible area with `port`'s
+/// R/W/X permissions. Nothing is actually mapped yet: pages are allocated
+/// lazily by `handle_lazy_fault` the first time each one is touched, so a
+/// large sparse `mmap` only pays for the pages it uses.
+pub fn mm_map(token: usize, start: usize, len: usize, port: usize) -> isize {
+    let perm = MapPermission::from_port(port);
+    let mut sets = MEMORY_SETS.exclusive_access();
+    let set = sets.entry(token).or_insert_with(|| MemorySet::from_token(token));
+    set.insert_lazy_area(VirtAddr::from(start), VirtAddr::from(start + len), perm);
+    0
+}
+
+/// Unmap the area starting at `start`, returning its frames to the
+/// allocator. `len` isn't needed: an area is identified by its start VPN and
+/// removed in full, mirroring how `sys_munmap` is expected to be called with
+/// the exact range a matching `sys_mmap` returned.
+pub fn mm_unmap(token: usize, start: usize, _len: usize) -> isize {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    match sets.get_mut(&token) {
+        Some(set) => set.remove_area_with_start_vpn(VirtAddr::from(start).floor()),
+        None => -1,
+    }
+}
+
+/// Which kind of access a page fault was trying to make, for checking
+/// against a lazy area's [`MapPermission`]. Mirrors the three RISC-V page
+/// fault causes (`scause` instruction/load/store page fault), which the
+/// trap handler that would call `handle_lazy_fault` lives outside this
+/// snapshot and would translate its `Trap`/`Exception` into.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FaultCause {
+    /// Load page fault
+    Read,
+    /// Store page fault
+    Write,
+    /// Instruction page fault
+    Exec,
+}
+
+/// Handle a page fault on a lazily-registered `mmap` area: if `vpn` falls
+/// inside one and `cause` is allowed by its permissions, allocate and map a
+/// frame for just that page and return `0` so the faulting instruction can
+/// be retried. Returns `-1` for a fault outside every lazy area, one whose
+/// permissions don't allow `cause` (e.g. a store into a read-only area), or
+/// a page that's already populated — the caller should treat `-1` as fatal
+/// and kill the task.
+pub fn handle_lazy_fault(token: usize, vpn: VirtPageNum, cause: FaultCause) -> isize {
+    let mut sets = MEMORY_SETS.exclusive_access();
+    let set = match sets.get_mut(&token) {
+        Some(set) => set,
+        None => return -1,
+    };
+    let area = match set
+        .areas
+        .iter_mut()
+        .find(|area| area.lazy && vpn >= area.vpn_start && vpn < area.vpn_end)
+    {
+        Some(area) => area,
+        None => return -1,
+    };
+    let required = match cause {
+        FaultCause::Read => MapPermission::R,
+        FaultCause::Write => MapPermission::W,
+        FaultCause::Exec => MapPermission::X,
+    };
+    if !area.map_perm.contains(required) || area.frames.contains_key(&vpn) {
+        return -1;
+    }
+
+    let flags = PTEFlags::from_bits_truncate(area.map_perm.bits());
+    let frame = match frame_alloc() {
+        Some(frame) => frame,
+        None => return -1,
+    };
+    if set.page_table.map_huge(vpn, frame.ppn, flags, PageSize::Size4KiB) != 0 {
+        return -1;
+    }
+    let area = set
+        .areas
+        .iter_mut()
+        .find(|area| area.lazy && vpn >= area.vpn_start && vpn < area.vpn_end)
+        .unwrap();
+    area.frames.insert(vpn, Arc::new(frame));
+    super::swap::track_resident(token, vpn);
+    0
+}
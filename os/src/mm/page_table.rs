@@ -40,6 +40,48 @@ impl PTEFlags {
     }
 }
 
+/// Sv39 leaf page sizes. An intermediate table entry has `V=1` and
+/// `R=W=X=0`; any of `R`/`W`/`X` set makes the entry a leaf, so a leaf can
+/// live at any of the three walk levels instead of only the last one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PageSize {
+    /// 4 KiB leaf, installed at the last walk step (`i == 2`)
+    Size4KiB,
+    /// 2 MiB leaf, installed one step early (`i == 1`)
+    Size2MiB,
+    /// 1 GiB leaf, installed at the root step (`i == 0`)
+    Size1GiB,
+}
+
+impl PageSize {
+    /// The walk step (as used by `find_pte`/`find_pte_create`'s `i`) at
+    /// which a leaf of this size is written
+    fn walk_stop(self) -> usize {
+        match self {
+            PageSize::Size4KiB => 2,
+            PageSize::Size2MiB => 1,
+            PageSize::Size1GiB => 0,
+        }
+    }
+    /// Size in bytes
+    pub fn size(self) -> usize {
+        match self {
+            PageSize::Size4KiB => 1 << 12,
+            PageSize::Size2MiB => 1 << 21,
+            PageSize::Size1GiB => 1 << 30,
+        }
+    }
+    /// Number of low VPN bits that must be zero for a VPN to be a valid
+    /// base for a leaf of this size
+    fn align_bits(self) -> usize {
+        match self {
+            PageSize::Size4KiB => 0,
+            PageSize::Size2MiB => 9,
+            PageSize::Size1GiB => 18,
+        }
+    }
+}
+
 
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -84,8 +126,43 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// A leaf maps a page directly (any of R/W/X set); a non-leaf is a
+    /// pointer to the next-level table (V set, R=W=X=0)
+    pub fn is_leaf(&self) -> bool {
+        (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+    /// Is this leaf a copy-on-write page, marked via the Sv39 RSW bit 8
+    /// (software-reserved, ignored by the MMU)?
+    pub fn is_cow(&self) -> bool {
+        self.bits & PTE_COW != 0
+    }
+    /// Set or clear the copy-on-write marker without touching any other bit
+    pub fn set_cow(&mut self, cow: bool) {
+        if cow {
+            self.bits |= PTE_COW;
+        } else {
+            self.bits &= !PTE_COW;
+        }
+    }
+    /// Has the MMU set `A` since it was last cleared?
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+    /// Has the MMU set `D` (a store has happened) since the page was mapped?
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
 }
 
+/// Software-reserved bit (Sv39 PTE bit 8, the low RSW bit) used to mark a
+/// copy-on-write leaf: present but write-protected pending
+/// `handle_cow_fault`
+const PTE_COW: usize = 1 << 8;
+/// Software-reserved bit (Sv39 PTE bit 9, the high RSW bit) used to mark a
+/// swapped-out leaf: `V` is clear and the swap slot id lives in the bits
+/// that would otherwise hold the physical page number
+const PTE_SWAPPED: usize = 1 << 9;
+
 /// page table structure
 pub struct PageTable {
     root_ppn: PhysPageNum,
@@ -111,12 +188,17 @@ impl PageTable {
     }
     /// Find PageTableEntry by VirtPageNum, create a frame for a 4KB page table if not exist
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_create_sized(vpn, PageSize::Size4KiB)
+    }
+    /// Like `find_pte_create`, but stops the walk early to land on a huge-page leaf
+    fn find_pte_create_sized(&mut self, vpn: VirtPageNum, size: PageSize) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
+        let stop = size.walk_stop();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == stop {
                 result = Some(pte);
                 break;
             }
@@ -129,14 +211,47 @@ impl PageTable {
         }
         result
     }
-    /// Find PageTableEntry by VirtPageNum
+    /// Find PageTableEntry by VirtPageNum. A leaf may be found before the
+    /// last walk step if it maps a huge page, so every step checks `is_leaf`
+    /// in addition to the usual `i == 2` stop.
     fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_with_level(vpn).map(|(pte, _level)| pte)
+    }
+    /// Like `find_pte`, but also returns the walk step the leaf was found at
+    /// (0/1/2), so a caller can tell a 1 GiB/2 MiB/4 KiB leaf apart to work
+    /// out how many low virtual-address bits it leaves unconsumed
+    fn find_pte_with_level(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        let mut result = None;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if !pte.is_valid() {
+                return None;
+            }
+            if i == 2 || pte.is_leaf() {
+                result = Some((pte, i));
+                break;
+            }
+            ppn = pte.ppn();
+        }
+        result
+    }
+    /// Like `find_pte`, but returns the leaf entry even when its `V` bit is
+    /// clear, by checking "is this the stop step" before "is this entry
+    /// valid" instead of after. `find_pte` can't be reused for a swapped-out
+    /// leaf because `mark_swapped` deliberately clears `V` on it; walking
+    /// with `find_pte` would bail out at that same step and report the page
+    /// as unmapped instead of swapped. Intermediate (non-leaf) steps still
+    /// require `V` to keep walking, since an invalid intermediate entry has
+    /// no next-level table to follow.
+    fn find_pte_raw(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == 2 || pte.is_leaf() {
                 result = Some(pte);
                 break;
             }
@@ -147,6 +262,24 @@ impl PageTable {
         }
         result
     }
+    /// Find a huge-page leaf at the walk step for `size`, without creating
+    /// intermediate tables
+    pub fn find_pte_huge(&self, vpn: VirtPageNum, size: PageSize) -> Option<PageTableEntry> {
+        let idxs = vpn.indexes();
+        let stop = size.walk_stop();
+        let mut ppn = self.root_ppn;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = ppn.get_pte_array()[*idx];
+            if !pte.is_valid() {
+                return None;
+            }
+            if i == stop {
+                return Some(pte);
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
     /// set the map between virtual page number and physical page number
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> isize{
@@ -159,6 +292,99 @@ impl PageTable {
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
         0
     }
+    /// Map a huge page: `vpn` must be aligned to `size` (its low
+    /// `size.align_bits()` bits zero) since a leaf's `ppn << 12` is the
+    /// mapped physical base
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags, size: PageSize) -> isize {
+        if vpn.0 & ((1 << size.align_bits()) - 1) != 0 {
+            println!("vpn {:#x} is not aligned for {:?}", vpn.0, size);
+            return -1;
+        }
+        let pte = self.find_pte_create_sized(vpn, size).unwrap();
+        if pte.is_valid() {
+            println!("vpn is mapped before mapping");
+            return -1;
+        }
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        0
+    }
+    /// Strip `W` from an already-mapped leaf and mark it copy-on-write,
+    /// leaving its physical page untouched. Used by `MemorySet::clone_cow`
+    /// on both the parent's and the child's copy of a shared page.
+    pub fn mark_cow(&mut self, vpn: VirtPageNum) -> isize {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() => {
+                let ro_flags = pte.flags() & !PTEFlags::W;
+                let ppn = pte.ppn();
+                *pte = PageTableEntry::new(ppn, ro_flags);
+                pte.set_cow(true);
+                0
+            }
+            _ => -1,
+        }
+    }
+    /// Clear `A` on an already-mapped leaf, e.g. when the clock hand passes
+    /// over it and gives it a second chance instead of evicting it. Every
+    /// other bit, including the copy-on-write marker, is left as it was.
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) -> isize {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() => {
+                pte.bits &= !(PTEFlags::A.bits as usize);
+                0
+            }
+            _ => -1,
+        }
+    }
+    /// Evict a resident leaf: clear `V`, stash `slot` where the physical
+    /// page number used to live, and mark it swapped. The other flag bits
+    /// (R/W/X/U/...) are left untouched so `restore_swapped` can bring the
+    /// page back with its original permissions.
+    pub fn mark_swapped(&mut self, vpn: VirtPageNum, slot: usize) -> isize {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() => {
+                let flags = (pte.flags() & !PTEFlags::V).bits as usize;
+                pte.bits = (slot << 10) | flags | PTE_SWAPPED;
+                0
+            }
+            _ => -1,
+        }
+    }
+    /// The swap slot a leaf was evicted to, if it's currently swapped out
+    pub fn swapped_slot(&self, vpn: VirtPageNum) -> Option<usize> {
+        let pte = self.find_pte_raw(vpn)?;
+        if !pte.is_valid() && (pte.bits & PTE_SWAPPED) != 0 {
+            Some(pte.bits >> 10)
+        } else {
+            None
+        }
+    }
+    /// Bring a swapped-out leaf back: point it at `ppn` and set `V`,
+    /// restoring the permissions it had before eviction
+    pub fn restore_swapped(&mut self, vpn: VirtPageNum, ppn: PhysPageNum) -> isize {
+        match self.find_pte_raw(vpn) {
+            Some(pte) if !pte.is_valid() && (pte.bits & PTE_SWAPPED) != 0 => {
+                let flags = pte.flags() | PTEFlags::V;
+                *pte = PageTableEntry::new(ppn, flags);
+                0
+            }
+            _ => -1,
+        }
+    }
+    /// Overwrite an *existing* leaf's physical page and flags in place,
+    /// without walking through `map`'s "already mapped" rejection. Used to
+    /// resolve a copy-on-write fault, either re-enabling `W` on the same
+    /// frame or repointing the leaf at a freshly copied one.
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> isize {
+        let pte = match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() => pte,
+            _ => {
+                println!("vpn is not mapped, cannot remap");
+                return -1;
+            }
+        };
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        0
+    }
     /// remove the map between virtual page number and physical page number
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) -> isize {
@@ -175,12 +401,96 @@ impl PageTable {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| *pte)
     }
+    /// Walk to the mapped leaf covering `va` and return its exact physical
+    /// address: the leaf's physical page base OR'd with however many low
+    /// bits of `va` the leaf doesn't consume. That's `va`'s 12-bit page
+    /// offset for an ordinary 4 KiB leaf, but a 2 MiB/1 GiB huge-page leaf
+    /// sits one/two walk steps early and leaves 21/30 low bits of `va`
+    /// unconsumed instead. Returns `None` if `va` isn't mapped.
+    pub fn translate_va(&self, va: VirtAddr) -> Option<usize> {
+        let (pte, level) = self.find_pte_with_level(va.floor())?;
+        if !pte.is_valid() {
+            return None;
+        }
+        let offset_bits = match level {
+            0 => 30, // 1 GiB leaf
+            1 => 21, // 2 MiB leaf
+            _ => 12, // 4 KiB leaf
+        };
+        let va: usize = va.into();
+        let offset_mask = (1usize << offset_bits) - 1;
+        Some(((pte.ppn().0 << 12) & !offset_mask) | (va & offset_mask))
+    }
     /// get the token from the page table
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
 }
 
+/// Why a checked user-pointer accessor refused a virtual address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessError {
+    /// No mapped leaf covers the address
+    Unmapped,
+    /// The leaf is mapped but lacks `U`, or lacks `R`/`W` for the access
+    /// being attempted
+    PermissionDenied,
+}
+
+/// Translate `va` and check it against the permissions a user-memory access
+/// needs: `U` always, plus `R` or `W` depending on `need_write`
+fn translate_checked(page_table: &PageTable, va: VirtAddr, need_write: bool) -> Result<usize, AccessError> {
+    let pte = page_table.translate(va.floor()).ok_or(AccessError::Unmapped)?;
+    if !pte.is_valid() || (pte.flags() & PTEFlags::U) == PTEFlags::empty() {
+        return Err(AccessError::PermissionDenied);
+    }
+    if need_write && !pte.writable() {
+        return Err(AccessError::PermissionDenied);
+    }
+    if !need_write && !pte.readable() {
+        return Err(AccessError::PermissionDenied);
+    }
+    page_table.translate_va(va).ok_or(AccessError::Unmapped)
+}
+
+/// Read a `T` out of user memory at `ptr`, checked against `U`/`R` instead
+/// of trusting the pointer. Doesn't handle a `T` that straddles a page
+/// boundary: only the page holding `ptr` is checked and translated.
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Result<&'static T, AccessError> {
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(ptr as usize);
+    let pa = translate_checked(&page_table, va, false)?;
+    Ok(unsafe { &*(pa as *const T) })
+}
+
+/// Like [`translated_ref`] but for a mutable user-memory write, checked
+/// against `U`/`W`
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, AccessError> {
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(ptr as usize);
+    let pa = translate_checked(&page_table, va, true)?;
+    Ok(unsafe { &mut *(pa as *mut T) })
+}
+
+/// Read a NUL-terminated user string starting at `ptr`, re-translating one
+/// byte at a time so it can safely cross page (and mapping) boundaries,
+/// checked against `U`/`R` throughout
+pub fn translated_str(token: usize, ptr: *const u8) -> Result<String, AccessError> {
+    let page_table = PageTable::from_token(token);
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        let pa = translate_checked(&page_table, VirtAddr::from(va), false)?;
+        let byte = unsafe { *(pa as *const u8) };
+        if byte == 0 {
+            break;
+        }
+        string.push(byte as char);
+        va += 1;
+    }
+    Ok(string)
+}
+
 /// Translate&Copy a ptr[u8] array with LENGTH len to a mutable u8 Vec through page table
 pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
     let page_table = PageTable::from_token(token);
@@ -208,66 +518,16 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     v
 }
 
-fn allocate_free_ppn() -> Option<PhysPageNum> {
-    // 调用frame_alloc函数来分配一个空闲的物理页帧
-    frame_alloc().map(|frame| frame.ppn)
-}
-
-pub fn mm_map(token: usize, start: usize, len: usize, port: usize) -> isize{
-    let flags = PTEFlags::from_usize((port << 1) | 0x09);
-    let mut page_table = PageTable::from_token(token);
-    let mut result = 0;
-    println!("[Kernel][mm_map]start = {}, len = {}", start, len);
-    println!("[Kernel][mm_map]flags = {:?}", flags);
-    let mut sta = start;
-    let end = start + len;
-    while sta < end {
-        let start_va = VirtAddr::from(sta);
-        let mut vpn = start_va.floor();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-
-        println!("[Kernel][mm_map]start_va = {}", usize::from(start_va));
-        println!("[Kernel][mm_map]vpn = {}", usize::from(vpn));
-
-        if let Some(ppn) = allocate_free_ppn() {
-            page_table.map(vpn, ppn, flags);
-        } else {
-            println!("[Kernel][mm_map]No free physical page available for mapping");
-            result = -1;
-            break;
+/// Largest page size that both starts aligned at `addr` and still fits
+/// within `remaining` bytes, preferring fewer/bigger leaves to cut TLB
+/// pressure for large contiguous regions. Nothing calls it at present:
+/// [`super::memory_set`] maps every page at `Size4KiB`.
+pub(super) fn largest_fitting_page_size(addr: usize, remaining: usize) -> PageSize {
+    for size in [PageSize::Size1GiB, PageSize::Size2MiB] {
+        if addr % size.size() == 0 && remaining >= size.size() {
+            return size;
         }
-
-        sta = end_va.into();
-        println!("[Kernel][mm_map]end sta = {}\n", usize::from(sta));
-    }
-    println!("[Kernel][mm_map] OK");
-    result
-}
-
-pub fn mm_unmap(token: usize, start: usize, len: usize) -> isize {
-    let mut page_table = PageTable::from_token(token);
-    let mut result = 0;
-    println!("[Kernel][mm_unmap]start = {}, len = {}", start, len);
-    let mut sta = start;
-    let end = start + len;
-    while sta < end {
-        let start_va = VirtAddr::from(sta);
-        let mut vpn = start_va.floor();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-
-        println!("[Kernel][mm_unmap]start_va = {}", usize::from(start_va));
-        println!("[Kernel][mm_unmap]vpn = {}", usize::from(vpn));
-
-        page_table.unmap(vpn);
-
-        sta = end_va.into();
-        println!("[Kernel][mm_unmap]end sta = {}\n", usize::from(sta));
     }
-    println!("[Kernel][mm_unmap] OK");
-    result
+    PageSize::Size4KiB
 }
 
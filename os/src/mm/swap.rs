@@ -0,0 +1,154 @@
+//! Clock (second-chance) page reclamation and swap-out
+//!
+//! [`track_resident`]/[`untrack_resident`] keep a circular list of every
+//! framed VPN currently backed by a real frame. [`reclaim_one`] sweeps that
+//! list once: a page with `A` set gets it cleared and a second lap instead
+//! of being evicted; the first page found with `A` already clear is evicted
+//! (written to swap first if `D` is set). [`handle_swap_in`] is the other
+//! half, called from a page-fault path on a PTE [`super::page_table::PageTable::swapped_slot`]
+//! recognises.
+//!
+//! Nothing in this snapshot actually calls `reclaim_one` when memory runs
+//! low — that hook belongs in the frame allocator's `frame_alloc`, which
+//! lives outside this tree — nor is there a trap handler here to route a
+//! swapped-out page fault into `handle_swap_in`. Both are wired up the way
+//! the rest of this module expects; only the call sites are missing.
+
+use super::memory_set;
+use super::{frame_alloc, VirtPageNum};
+use crate::sync::UPSafeCell;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// A pluggable 4 KiB-block backing store for swapped-out pages
+pub trait SwapDevice: Send + Sync {
+    /// Read the 4 KiB block at `slot` into `buf`
+    fn read_block(&self, slot: usize, buf: &mut [u8]);
+    /// Write `buf` (4 KiB) out to the block at `slot`
+    fn write_block(&self, slot: usize, buf: &[u8]);
+}
+
+struct NoSwapDevice;
+impl SwapDevice for NoSwapDevice {
+    fn read_block(&self, _slot: usize, _buf: &mut [u8]) {
+        panic!("swap-in requested but no SwapDevice has been installed");
+    }
+    fn write_block(&self, _slot: usize, _buf: &[u8]) {
+        panic!("swap-out requested but no SwapDevice has been installed");
+    }
+}
+
+lazy_static! {
+    static ref SWAP_DEVICE: UPSafeCell<Box<dyn SwapDevice>> =
+        unsafe { UPSafeCell::new(Box::new(NoSwapDevice)) };
+    static ref FREE_SLOTS: UPSafeCell<Vec<usize>> = unsafe { UPSafeCell::new(Vec::new()) };
+    static ref NEXT_SLOT: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
+    /// Resident framed pages eligible for reclamation, in clock-hand order
+    static ref CLOCK: UPSafeCell<VecDeque<(usize, VirtPageNum)>> =
+        unsafe { UPSafeCell::new(VecDeque::new()) };
+}
+
+/// Install the backing store `reclaim_one`/`handle_swap_in` read and write
+/// through. Call once during boot before demand paging can run.
+pub fn install_swap_device(device: Box<dyn SwapDevice>) {
+    *SWAP_DEVICE.exclusive_access() = device;
+}
+
+fn alloc_slot() -> usize {
+    if let Some(slot) = FREE_SLOTS.exclusive_access().pop() {
+        return slot;
+    }
+    let mut next = NEXT_SLOT.exclusive_access();
+    let slot = *next;
+    *next += 1;
+    slot
+}
+
+fn free_slot(slot: usize) {
+    FREE_SLOTS.exclusive_access().push(slot);
+}
+
+/// Register a newly mapped framed page as eligible for clock reclamation
+pub fn track_resident(token: usize, vpn: VirtPageNum) {
+    CLOCK.exclusive_access().push_back((token, vpn));
+}
+
+/// Drop a page from the reclamation list, e.g. because its area was unmapped
+pub fn untrack_resident(token: usize, vpn: VirtPageNum) {
+    CLOCK.exclusive_access().retain(|&(t, v)| !(t == token && v == vpn));
+}
+
+/// Sweep the clock hand until a page is evicted or every tracked page has
+/// been given its second chance this round. Returns the `(token, vpn)`
+/// evicted, or `None` if nothing was evictable.
+pub fn reclaim_one() -> Option<(usize, VirtPageNum)> {
+    let rounds = CLOCK.exclusive_access().len();
+    for _ in 0..rounds {
+        let (token, vpn) = CLOCK.exclusive_access().pop_front()?;
+        let accessed = memory_set::with_page_table(token, |pt| {
+            let accessed = pt.translate(vpn).map(|pte| pte.accessed()).unwrap_or(false);
+            if accessed {
+                pt.clear_accessed(vpn);
+            }
+            accessed
+        });
+        match accessed {
+            // The address space is gone; just drop it from the clock.
+            None => continue,
+            Some(true) => CLOCK.exclusive_access().push_back((token, vpn)),
+            Some(false) => {
+                evict(token, vpn);
+                return Some((token, vpn));
+            }
+        }
+    }
+    None
+}
+
+fn evict(token: usize, vpn: VirtPageNum) {
+    let dirty = memory_set::with_page_table(token, |pt| {
+        pt.translate(vpn).map(|pte| pte.dirty()).unwrap_or(false)
+    })
+    .unwrap_or(false);
+
+    let slot = alloc_slot();
+    if let Some(frame) = memory_set::take_resident_frame(token, vpn) {
+        if dirty {
+            SWAP_DEVICE
+                .exclusive_access()
+                .write_block(slot, frame.ppn.get_bytes_array());
+        }
+        // `frame` drops here; the physical page only actually returns to the
+        // allocator once every other `Arc<FrameTracker>` sharing it (e.g. a
+        // copy-on-write sibling) has dropped too.
+    }
+    memory_set::with_page_table(token, |pt| pt.mark_swapped(vpn, slot));
+}
+
+/// Fault handler for a swapped-out page: allocate a frame, read the slot
+/// back into it, and restore the PTE. Returns `-1` if `vpn` isn't currently
+/// swapped out or there's no free frame.
+pub fn handle_swap_in(token: usize, vpn: VirtPageNum) -> isize {
+    let slot = match memory_set::with_page_table(token, |pt| pt.swapped_slot(vpn)) {
+        Some(Some(slot)) => slot,
+        _ => return -1,
+    };
+    let frame = match frame_alloc() {
+        Some(frame) => frame,
+        None => return -1,
+    };
+    SWAP_DEVICE
+        .exclusive_access()
+        .read_block(slot, frame.ppn.get_bytes_array());
+    free_slot(slot);
+
+    let restored = memory_set::with_page_table(token, |pt| pt.restore_swapped(vpn, frame.ppn)).unwrap_or(-1);
+    if restored == 0 {
+        memory_set::install_resident_frame(token, vpn, Arc::new(frame));
+        track_resident(token, vpn);
+    }
+    restored
+}
@@ -6,8 +6,10 @@ use crate::{
         current_user_token,
         current_task_m_map,current_task_m_unmap,
         add_task_syscall_times,
+        current_task,
     },
-    mm::{translated_byte_buffer, mm_map, mm_unmap},
+    mm::{translated_byte_buffer, translated_str, mm_map, mm_unmap},
+    fs::{link_at, unlink_at},
     timer::get_time_us,
 };
 
@@ -87,6 +89,106 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
     // mm_unmap(token, start, len)
     current_task_m_unmap(start, len)
 }
+/// reposition an open file's offset, see `File::seek` for `whence` semantics
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    trace!("kernel: sys_lseek");
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    let file = match inner.fd_table[fd].clone() {
+        Some(file) => file,
+        None => return -1,
+    };
+    drop(inner);
+    file.seek(offset, whence)
+}
+
+/// set the stride-scheduling priority (`>= 2`) of the calling task, returns
+/// the new priority or `-1` if `prio` is out of range
+pub fn sys_set_priority(prio: isize) -> isize {
+    trace!("kernel: sys_set_priority");
+    if prio < 2 {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().priority = prio as usize;
+    prio
+}
+
+/// install a seccomp-style syscall allow-list on the calling task: `mode` 0
+/// kills the task on a forbidden syscall, `mode` 1 returns `-EPERM` instead;
+/// `bitmap_ptr` points to `len` `u64` words, 64 syscall ids each. Filters are
+/// immutable once set, so a second call fails with `-1`.
+pub fn sys_set_syscall_filter(mode: usize, bitmap_ptr: *const u64, len: usize) -> isize {
+    trace!("kernel: sys_set_syscall_filter");
+    use crate::task::{SyscallFilter, SyscallFilterMode};
+    let mode = match mode {
+        0 => SyscallFilterMode::Kill,
+        1 => SyscallFilterMode::Deny,
+        _ => return -1,
+    };
+    let token = current_user_token();
+    let v = translated_byte_buffer(token, bitmap_ptr as *const u8, len * core::mem::size_of::<u64>());
+    let mut bitmap = alloc::vec::Vec::with_capacity(len);
+    for slice in v {
+        for chunk in slice.chunks_exact(core::mem::size_of::<u64>()) {
+            bitmap.push(u64::from_ne_bytes(chunk.try_into().unwrap()));
+        }
+    }
+
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.syscall_filter.is_some() {
+        return -1;
+    }
+    inner.syscall_filter = Some(alloc::sync::Arc::new(SyscallFilter::new(mode, bitmap)));
+    0
+}
+
+/// create a hard link `new_path` pointing at the same file as `old_path`
+pub fn sys_linkat(old_path: *const u8, new_path: *const u8) -> isize {
+    trace!("kernel: sys_linkat");
+    let token = current_user_token();
+    let old_path = match translated_str(token, old_path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    let new_path = match translated_str(token, new_path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    link_at(&old_path, &new_path)
+}
+
+/// remove a hard link, freeing the file once its last link is gone
+pub fn sys_unlinkat(path: *const u8) -> isize {
+    trace!("kernel: sys_unlinkat");
+    let token = current_user_token();
+    let path = match translated_str(token, path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    unlink_at(&path)
+}
+
+/// device-specific control on an open file descriptor, see `File::ioctl`
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    trace!("kernel: sys_ioctl");
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    let file = match inner.fd_table[fd].clone() {
+        Some(file) => file,
+        None => return -1,
+    };
+    drop(inner);
+    file.ioctl(cmd, arg)
+}
+
 /// change data segment size
 pub fn sys_sbrk(size: i32) -> isize {
     trace!("kernel: sys_sbrk");
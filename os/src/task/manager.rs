@@ -1,6 +1,6 @@
 //!Implementation of [`TaskManager`]
+use super::spinlock::SpinMutex;
 use super::{current_task, TaskControlBlock};
-use crate::sync::UPSafeCell;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -9,7 +9,16 @@ pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
-const BIG_STRIDE:isize = 0x10000000;
+const BIG_STRIDE: usize = 0x10000000;
+
+/// Stride-scheduling pass comparison that is safe under wraparound: as long
+/// as `max_pass - min_pass <= BIG_STRIDE` (guaranteed because every step is
+/// `<= BIG_STRIDE / 2` when `priority >= 2`), comparing the wrapping
+/// difference as a signed value gives the correct ordering even after `a`
+/// or `b` has wrapped around `usize::MAX`.
+fn stride_lt(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
 
 /// A simple FIFO scheduler.
 impl TaskManager {
@@ -28,7 +37,12 @@ impl TaskManager {
         self.ready_queue.pop_front()
     }
 
+    /// Pick the ready task with the smallest stride pass, advance its pass by
+    /// `BIG_STRIDE / priority`, and remove it from the ready queue
     pub fn fetch_min_step_and_add_pass(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.ready_queue.is_empty() {
+            return None;
+        }
         let mut min_tcb = self.ready_queue[0].clone();
         let mut min_task = min_tcb.inner_exclusive_access();
         let mut min_stride = min_task.stride;
@@ -36,7 +50,7 @@ impl TaskManager {
 
         for tcb in &self.ready_queue {
             let task = tcb.inner_exclusive_access();
-            if task.stride < min_stride {
+            if stride_lt(task.stride, min_stride) {
                 min_tcb = tcb.clone();
                 min_stride = task.stride;
             }
@@ -47,7 +61,7 @@ impl TaskManager {
         }
 
         let mut min_task = min_tcb.inner_exclusive_access();
-        min_task.stride = min_task.stride + BIG_STRIDE / min_task.priority;
+        min_task.stride = min_task.stride.wrapping_add(BIG_STRIDE / min_task.priority);
         drop(min_task);
 
         // self.add(min_tcb.clone());
@@ -57,9 +71,9 @@ impl TaskManager {
 }
 
 lazy_static! {
-    /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// TASK_MANAGER instance through lazy_static!. Shared across every hart,
+    /// so it's guarded by a real [`SpinMutex`] rather than `UPSafeCell`.
+    pub static ref TASK_MANAGER: SpinMutex<TaskManager> = SpinMutex::new(TaskManager::new());
 }
 
 /// Add process to ready queue
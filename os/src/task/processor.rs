@@ -3,16 +3,37 @@
 //! Here, the continuous operation of user apps in CPU is maintained,
 //! the current running state of CPU is recorded,
 //! and the replacement and transfer of control flow of different applications are executed.
-
+//!
+//! Scheduling is now per-hart: each hart owns its own [`Processor`] (current
+//! task + idle control flow) while `TASK_MANAGER` stays the single shared
+//! ready queue, so harts pull from the same stride-ordered pool. This relies
+//! on `crate::config::MAX_HARTS` (bumped alongside the boot code that parks
+//! secondary harts into [`run_tasks`]) and on [`PROCESSORS`] being guarded by
+//! a real [`super::spinlock::SpinMutex`] rather than `UPSafeCell`, which only
+//! protects against reentrancy on a single hart, not concurrent access from
+//! several.
+
+use super::spinlock::SpinMutex;
 use super::{__switch, TaskInfo};
 use super::{fetch_task, TaskStatus, fetch_min_task};
 use super::{TaskContext, TaskControlBlock};
-use crate::sync::UPSafeCell;
+use crate::config::MAX_HARTS;
 use crate::trap::TrapContext;
 use crate::timer::get_time_ms;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
+/// Read this hart's id. Boot code stashes it in `tp` before jumping to Rust,
+/// so every hart can cheaply find its own [`Processor`] without a lock.
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        core::arch::asm!("mv {0}, tp", out(reg) id);
+    }
+    id
+}
+
 /// Processor management structure
 pub struct Processor {
     ///The task currently executing on the current processor
@@ -86,14 +107,27 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One [`Processor`] per hart, indexed by [`hart_id`]. `TASK_MANAGER`
+    /// remains the single shared ready queue, so the stride ordering is
+    /// preserved across harts; only the "currently running task" slot is
+    /// now per-hart rather than global. Each slot is still reachable from
+    /// every hart through this shared `Vec`, so it's guarded by a real
+    /// [`SpinMutex`] rather than `UPSafeCell`.
+    static ref PROCESSORS: Vec<SpinMutex<Processor>> =
+        (0..MAX_HARTS).map(|_| SpinMutex::new(Processor::new())).collect();
+}
+
+fn this_hart_processor() -> &'static SpinMutex<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 ///The main part of process execution and scheduling
-///Loop `fetch_task` to get the process that needs to run, and switch the process through `__switch`
+///Loop `fetch_min_task` to get the process that needs to run, and switch the process through `__switch`.
+///Every hart (boot and secondary) runs its own copy of this loop, all pulling from the same
+///shared ready queue via `fetch_min_task`.
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = this_hart_processor().exclusive_access();
         if let Some(task) = fetch_min_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
@@ -117,12 +151,12 @@ pub fn run_tasks() {
 
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    this_hart_processor().exclusive_access().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    this_hart_processor().exclusive_access().current()
 }
 
 /// Get the current user token(addr of page table)
@@ -141,7 +175,7 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
 
 ///Return to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = this_hart_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
@@ -150,17 +184,51 @@ pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
 }
 
 pub fn get_current_processor_info() -> TaskInfo {
-    PROCESSOR.exclusive_access().get_current_task_info()
+    this_hart_processor().exclusive_access().get_current_task_info()
 }
 
 pub fn add_processor_syscall_times(syscall: usize){
-    PROCESSOR.exclusive_access().update_task_info(syscall, true);
+    this_hart_processor().exclusive_access().update_task_info(syscall, true);
+}
+
+/// Errno returned by a syscall denied through a [`SyscallFilter`]
+pub const EPERM: isize = -1;
+
+/// Check the current task's syscall filter (if any) before `syscall_id`
+/// runs. The single dispatch point funneling all syscalls (alongside
+/// `add_processor_syscall_times`) should call this first and, on `Some(err)`,
+/// return `err` instead of invoking the handler.
+pub fn enforce_syscall_filter(syscall_id: usize) -> Option<isize> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let filter = inner.syscall_filter.clone()?;
+    if filter.is_allowed(syscall_id) {
+        return None;
+    }
+    match filter.mode() {
+        super::SyscallFilterMode::Deny => Some(EPERM),
+        super::SyscallFilterMode::Kill => {
+            drop(inner);
+            super::exit_current_and_run_next();
+            unreachable!("exit_current_and_run_next does not return");
+        }
+    }
 }
 
 pub fn current_processor_m_map(start: usize, len: usize, port: usize) -> isize {
-    PROCESSOR.exclusive_access().current_task_m_map(start, len, port)
+    this_hart_processor().exclusive_access().current_task_m_map(start, len, port)
 }
 
 pub fn current_processor_m_unmap(start: usize, len: usize) -> isize {
-    PROCESSOR.exclusive_access().current_task_m_unmap(start, len)
+    this_hart_processor().exclusive_access().current_task_m_unmap(start, len)
+}
+
+/// `(start, end)` ranges the current task has mapped through `sys_mmap`,
+/// used to render `/proc/<pid>/maps`
+pub fn current_task_mmap_ranges() -> alloc::vec::Vec<(usize, usize)> {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .mmap_ranges
+        .clone()
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+//! A real mutual-exclusion primitive for state shared across harts.
+//!
+//! `crate::sync::UPSafeCell` (used everywhere else in this kernel) is a
+//! `RefCell` wrapped for a trusted single-hart caller: it panics on
+//! reentrant access from the *same* hart, but does nothing to stop two
+//! different harts from both entering `exclusive_access()` at once.
+//! That's fine for genuinely per-hart state, but `TASK_MANAGER`'s ready
+//! queue and `PROCESSORS` are shared across every hart and need an actual
+//! lock. [`SpinMutex`] is that lock: a spin-wait `AtomicBool` guarding a
+//! `T`, exposing the same `exclusive_access()` name `UPSafeCell` uses so
+//! call sites only need their field's type changed, not their call sites.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A spin-wait mutex, safe to share across harts.
+pub struct SpinMutex<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinMutex<T> {}
+
+impl<T> SpinMutex<T> {
+    /// Wrap `data` behind the lock
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Spin until the lock is acquired, then return an exclusive guard.
+    /// Named to match `UPSafeCell::exclusive_access` so the two types are
+    /// interchangeable at call sites.
+    pub fn exclusive_access(&self) -> SpinMutexGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinMutexGuard { lock: self }
+    }
+}
+
+/// RAII guard releasing a [`SpinMutex`] when dropped
+pub struct SpinMutexGuard<'a, T> {
+    lock: &'a SpinMutex<T>,
+}
+
+impl<'a, T> Deref for SpinMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
@@ -1,18 +1,145 @@
 //! Types related to task management
 
 use crate::config::MAX_SYSCALL_NUM;
+use crate::fs::File;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::RefMut;
 use super::TaskContext;
 
 /// The task control block (TCB) of a task.
-#[derive(Copy, Clone)]
 pub struct TaskControlBlock {
+    /// Mutable task state, wrapped so the scheduler and syscall handlers can
+    /// reach it through a shared `Arc<TaskControlBlock>`
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// Mutable state of a [`TaskControlBlock`]
+pub struct TaskControlBlockInner {
     pub task_info: TaskInfo,
     /// The task status in it's lifecycle
     pub task_status: TaskStatus,
     /// The task context
     pub task_cx: TaskContext,
     /// The task Start time(ms)
-    pub task_start_time: usize
+    pub task_start_time: usize,
+    /// Token of this task's page table, used to service `sys_mmap`/`sys_munmap`
+    pub page_table_token: usize,
+    /// `(start, end)` virtual address ranges installed by `sys_mmap`, kept
+    /// around so `sys_munmap` and `/proc`-style introspection can find them
+    pub mmap_ranges: Vec<(usize, usize)>,
+    /// Open file descriptor table, indexed by fd
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// Cumulative stride-scheduling "pass", advanced by `BIG_STRIDE / priority`
+    /// each time this task is scheduled. Wraps around `usize::MAX`.
+    pub stride: usize,
+    /// Stride-scheduling priority, always `>= 2`. Higher priority means a
+    /// smaller step per schedule, i.e. more CPU share.
+    pub priority: usize,
+    /// Seccomp-style syscall allow-list, installed at most once by
+    /// `sys_set_syscall_filter` and inherited by children
+    pub syscall_filter: Option<Arc<SyscallFilter>>,
+}
+
+/// What happens when a task invokes a syscall its [`SyscallFilter`] forbids
+#[derive(Copy, Clone, PartialEq)]
+pub enum SyscallFilterMode {
+    /// terminate the offending task
+    Kill,
+    /// return `-EPERM` from the syscall instead of running it
+    Deny,
+}
+
+/// A per-task syscall allow-list, installed through `sys_set_syscall_filter`.
+/// Immutable once set: a sandboxed task cannot widen its own privileges.
+pub struct SyscallFilter {
+    mode: SyscallFilterMode,
+    /// bitmap of allowed syscall ids, 64 ids per word
+    bitmap: Vec<u64>,
+}
+
+impl SyscallFilter {
+    /// Build a filter from a mode and a syscall-id bitmap
+    pub fn new(mode: SyscallFilterMode, bitmap: Vec<u64>) -> Self {
+        Self { mode, bitmap }
+    }
+    /// What to do when a forbidden syscall is attempted
+    pub fn mode(&self) -> SyscallFilterMode {
+        self.mode
+    }
+    /// Is `syscall_id` permitted by this filter?
+    pub fn is_allowed(&self, syscall_id: usize) -> bool {
+        let word = syscall_id / 64;
+        let bit = syscall_id % 64;
+        self.bitmap
+            .get(word)
+            .map_or(false, |w| w & (1 << bit) != 0)
+    }
+}
+
+/// Priority newly spawned tasks start out with
+pub const DEFAULT_PRIORITY: usize = 16;
+
+impl TaskControlBlock {
+    /// Wrap a freshly initialized inner state into a TCB. `parent_syscall_filter`
+    /// should be the spawning task's `syscall_filter`, so a sandboxed process
+    /// can't widen its own privileges by spawning a child with none; pass
+    /// `None` for the initial task, which has no parent to inherit from.
+    pub fn new(
+        task_status: TaskStatus,
+        task_cx: TaskContext,
+        task_start_time: usize,
+        page_table_token: usize,
+        parent_syscall_filter: Option<Arc<SyscallFilter>>,
+    ) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    task_info: TaskInfo::new(task_status),
+                    task_status,
+                    task_cx,
+                    task_start_time,
+                    page_table_token,
+                    mmap_ranges: Vec::new(),
+                    fd_table: Vec::new(),
+                    stride: 0,
+                    priority: DEFAULT_PRIORITY,
+                    syscall_filter: parent_syscall_filter,
+                })
+            },
+        }
+    }
+    /// Exclusive access to the task's mutable state
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Token of this task's page table (used as `satp` on a switch)
+    pub fn get_user_token(&self) -> usize {
+        self.inner_exclusive_access().page_table_token
+    }
+}
+
+impl TaskControlBlockInner {
+    /// Map `[start, start+len)` into this task's address space and remember
+    /// the range for `sys_munmap`/introspection
+    pub fn m_map(&mut self, start: usize, len: usize, port: usize) -> isize {
+        let result = crate::mm::mm_map(self.page_table_token, start, len, port);
+        if result == 0 {
+            self.mmap_ranges.push((start, start + len));
+        }
+        result
+    }
+    /// Unmap the whole `mmap` area starting at `start` from this task's
+    /// address space (`mm_unmap` ignores `len`); the recorded range is
+    /// dropped only if it is exactly `[start, start+len)`
+    pub fn m_unmap(&mut self, start: usize, len: usize) -> isize {
+        let result = crate::mm::mm_unmap(self.page_table_token, start, len);
+        if result == 0 {
+            self.mmap_ranges.retain(|&(s, e)| (s, e) != (start, start + len));
+        }
+        result
+    }
 }
 
 /// The status of a task
@@ -28,6 +155,18 @@ pub enum TaskStatus {
     Exited,
 }
 
+impl core::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            TaskStatus::UnInit => "UnInit",
+            TaskStatus::Ready => "Ready",
+            TaskStatus::Running => "Running",
+            TaskStatus::Exited => "Exited",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// Task information
 #[allow(dead_code)]
 #[derive(Copy, Clone)]
@@ -56,6 +195,21 @@ impl TaskInfo {
         self.status = status;
     }
 
+    /// Current status of the task
+    pub fn status(&self) -> TaskStatus {
+        self.status
+    }
+
+    /// Per-syscall invocation counts, indexed by syscall id
+    pub fn syscall_times(&self) -> [u32; MAX_SYSCALL_NUM] {
+        self.syscall_times
+    }
+
+    /// Accumulated running time of the task in milliseconds
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
     pub fn add_syscall_time(&mut self, index: usize) {
         if index < MAX_SYSCALL_NUM {
             self.syscall_times[index] += 1;